@@ -1,23 +1,50 @@
 use std::{
     collections::HashMap,
     env,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use axum::{
-    Json, Router,
-    body::Body,
+    body::{Body, Bytes},
     extract::{Query, State},
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
+    Json, Router,
 };
-use futures_util::TryStreamExt;
+use futures_util::{stream, StreamExt, TryStreamExt};
 use jsonschema::JSONSchema;
+use rand::Rng;
 use regex::Regex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, json};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tower_http::trace::TraceLayer;
+use tracing::{info, instrument, warn};
+
+#[path = "../json_repair.rs"]
+mod json_repair;
+#[path = "proxy_server/metrics.rs"]
+mod metrics;
+#[path = "proxy_server/providers.rs"]
+mod providers;
+use providers::{provider_from_name, Provider};
+
+/// Monotonically increasing id used to correlate log lines for one request.
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn next_request_id() -> u64 {
+    REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Opening/closing markers the model is instructed to wrap tool calls in.
+const TOOL_OPEN_TAG: &str = "<tool_code>";
+const TOOL_CLOSE_TAG: &str = "</tool_code>";
 
 const TOOL_SYSTEM_PROMPT: &str = r#"
 ### TOOL USE INSTRUCTIONS
@@ -42,12 +69,92 @@ struct AppState {
     positron_url: String,
     positron_key: String,
     max_retries: usize,
+    max_steps: usize,
     http: Client,
     tool_regex: Regex,
+    tool_executors: Arc<HashMap<String, Arc<dyn ToolExecutor>>>,
+    /// Default upstream provider, used unless a request overrides it via the
+    /// `x-provider` header.
+    default_provider_name: String,
+    /// Bounds the number of requests served concurrently; requests beyond
+    /// this are rejected with `503` instead of queuing unbounded upstream
+    /// connections.
+    concurrency: Arc<Semaphore>,
+    /// Base delay for exponential backoff between transport-failure retries.
+    retry_base_delay_ms: u64,
+    /// Upper bound on the backoff delay, regardless of attempt count.
+    retry_max_delay_ms: u64,
+    /// Whether a transport failure (timeout, connection error, 5xx) consumes
+    /// one of the turn's `max_retries`, or is retried on its own budget.
+    count_transport_errors_against_retries: bool,
+}
+
+/// Resolves the provider for one request: the `x-provider` header if
+/// present, otherwise `AppState::default_provider_name`.
+fn resolve_provider(headers: &HeaderMap, state: &AppState) -> Box<dyn Provider> {
+    let name = headers
+        .get("x-provider")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or(&state.default_provider_name);
+    provider_from_name(name)
+}
+
+/// Runs a registered tool server-side as part of the agentic step loop.
+/// Implementations must be safe to share across concurrent requests.
+#[async_trait::async_trait]
+trait ToolExecutor: Send + Sync {
+    async fn call(&self, args: Value) -> Result<Value, String>;
+}
+
+/// Forwards a tool's `arguments` to a configured HTTP endpoint and returns
+/// its JSON response as the tool result.
+struct HttpToolExecutor {
+    url: String,
+    http: Client,
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for HttpToolExecutor {
+    async fn call(&self, args: Value) -> Result<Value, String> {
+        let resp = self
+            .http
+            .post(&self.url)
+            .json(&args)
+            .send()
+            .await
+            .map_err(|e| format!("tool executor request failed: {e}"))?;
+        resp.json::<Value>()
+            .await
+            .map_err(|e| format!("tool executor returned invalid JSON: {e}"))
+    }
+}
+
+/// Builds the tool executor registry from `TOOL_EXECUTOR_URLS`, a JSON object
+/// mapping tool name -> HTTP endpoint, e.g. `{"get_weather":"http://localhost:9001"}`.
+fn build_tool_executors(http: &Client) -> HashMap<String, Arc<dyn ToolExecutor>> {
+    let mut registry: HashMap<String, Arc<dyn ToolExecutor>> = HashMap::new();
+    let Ok(raw) = env::var("TOOL_EXECUTOR_URLS") else {
+        return registry;
+    };
+    let Ok(Value::Object(map)) = serde_json::from_str::<Value>(&raw) else {
+        tracing::warn!("TOOL_EXECUTOR_URLS is not a valid JSON object; ignoring");
+        return registry;
+    };
+    for (name, url) in map {
+        let Some(url) = url.as_str() else { continue };
+        registry.insert(
+            name,
+            Arc::new(HttpToolExecutor {
+                url: url.to_string(),
+                http: http.clone(),
+            }),
+        );
+    }
+    registry
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Tool {
+pub(crate) struct Tool {
     #[serde(rename = "type")]
     type_field: String,
     function: ToolFunction,
@@ -77,34 +184,39 @@ struct ToolCallFunction {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Message {
-    role: String,
+pub(crate) struct Message {
+    pub(crate) role: String,
     #[serde(default)]
-    content: Option<Value>,
+    pub(crate) content: Option<Value>,
     #[serde(default)]
-    tool_calls: Option<Vec<ToolCall>>,
+    pub(crate) tool_calls: Option<Vec<ToolCall>>,
     #[serde(default)]
-    name: Option<String>,
+    pub(crate) name: Option<String>,
     #[serde(default, rename = "tool_call_id")]
-    tool_call_id: Option<String>,
+    pub(crate) tool_call_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Choice {
-    message: Message,
+pub(crate) struct Choice {
+    pub(crate) message: Message,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct CompletionResponse {
-    choices: Vec<Choice>,
+pub(crate) struct CompletionResponse {
+    pub(crate) choices: Vec<Choice>,
     #[serde(flatten)]
-    extra: HashMap<String, Value>,
+    pub(crate) extra: HashMap<String, Value>,
 }
 
 #[derive(Deserialize)]
 struct ProxyOptions {
     #[serde(default = "default_true")]
     use_raph: bool,
+    /// When true, the proxy executes validated tool calls itself via the
+    /// registered `ToolExecutor`s and continues the conversation, instead of
+    /// handing `tool_calls` straight back to the client.
+    #[serde(default)]
+    execute_tools: bool,
 }
 
 fn default_true() -> bool {
@@ -114,6 +226,8 @@ fn default_true() -> bool {
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
+    tracing_subscriber::fmt::init();
+
     let positron_url =
         env::var("POSITRON_URL").unwrap_or_else(|_| "http://localhost:8080/v1".to_string());
     let positron_key = env::var("POSITRON_KEY").unwrap_or_else(|_| "sk-placeholder".to_string());
@@ -122,16 +236,77 @@ async fn main() {
         .and_then(|v| v.parse::<usize>().ok())
         .unwrap_or(3);
 
+    let max_steps = env::var("MAX_STEPS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(5);
+
+    let max_concurrency = env::var("MAX_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(64);
+
+    let connect_timeout_ms = env::var("CONNECT_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5_000);
+    let request_timeout_ms = env::var("REQUEST_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(120_000);
+
+    let retry_base_delay_ms = env::var("RETRY_BASE_DELAY_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(200);
+    let retry_max_delay_ms = env::var("RETRY_MAX_DELAY_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5_000);
+    let count_transport_errors_against_retries = env::var("COUNT_TRANSPORT_ERRORS_AGAINST_RETRIES")
+        .ok()
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(true);
+
+    let mut http_builder = Client::builder()
+        .connect_timeout(Duration::from_millis(connect_timeout_ms))
+        .timeout(Duration::from_millis(request_timeout_ms));
+    if let Ok(proxy_url) = env::var("HTTP_PROXY") {
+        match reqwest::Proxy::http(&proxy_url) {
+            Ok(proxy) => http_builder = http_builder.proxy(proxy),
+            Err(e) => tracing::warn!(error = %e, "invalid HTTP_PROXY, ignoring"),
+        }
+    }
+    let http = http_builder.build().expect("failed to build http client");
+
+    let tool_executors = Arc::new(build_tool_executors(&http));
+
+    let default_provider_name = env::var("PROVIDER").unwrap_or_else(|_| "openai".to_string());
+
     let state = AppState {
         positron_url,
         positron_key,
         max_retries,
-        http: Client::new(),
+        max_steps,
+        http,
         tool_regex: Regex::new(r"(?s)<tool_code>(.*?)</tool_code>").expect("regex"),
+        tool_executors,
+        default_provider_name,
+        concurrency: Arc::new(Semaphore::new(max_concurrency)),
+        retry_base_delay_ms,
+        retry_max_delay_ms,
+        count_transport_errors_against_retries,
     };
 
+    let metrics_handle = metrics::install_recorder();
+
     let app = Router::new()
         .route("/v1/chat/completions", post(proxy_handler))
+        .route(
+            "/metrics",
+            get(move || async move { metrics_handle.render() }),
+        )
+        .layer(TraceLayer::new_for_http())
         .with_state(state.clone());
 
     let port: u16 = env::var("PROXY_PORT")
@@ -151,12 +326,24 @@ async fn main() {
     axum::serve(listener, app).await.expect("server crashed");
 }
 
+#[instrument(skip_all, fields(request_id = next_request_id()))]
 async fn proxy_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
     Query(opts): Query<ProxyOptions>,
     Json(mut body): Json<Value>,
 ) -> Response {
+    metrics::record_request();
+
+    let Ok(permit) = state.concurrency.clone().try_acquire_owned() else {
+        metrics::record_concurrency_limited();
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "Too many concurrent requests, try again shortly." })),
+        )
+            .into_response();
+    };
+
     // Bypass logic via header or URL param.
     let header_bypass = headers
         .get("x-raph-mode")
@@ -164,17 +351,24 @@ async fn proxy_handler(
         .map(|v| v.eq_ignore_ascii_case("off") || v.eq_ignore_ascii_case("false"))
         .unwrap_or(false);
     let url_bypass = !opts.use_raph;
+    let provider = resolve_provider(&headers, &state);
 
     // If bypass requested, forward directly (respect stream flag).
     if header_bypass || url_bypass {
+        metrics::record_passthrough();
         let wants_stream = body
             .get("stream")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
+        if wants_stream && !provider.supports_streaming() {
+            return unsupported_streaming_response();
+        }
         if wants_stream {
-            return forward_passthrough_stream(&state, body).await;
+            return forward_passthrough_stream(&state, &*provider, body, permit).await;
         } else {
-            return forward_passthrough_json(&state, body).await.into_response();
+            return forward_passthrough_json(&state, &*provider, body)
+                .await
+                .into_response();
         }
     }
 
@@ -195,17 +389,25 @@ async fn proxy_handler(
     };
 
     if tools.is_empty() {
+        metrics::record_passthrough();
         let wants_stream = body
             .get("stream")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
+        if wants_stream && !provider.supports_streaming() {
+            return unsupported_streaming_response();
+        }
         if wants_stream {
-            return forward_passthrough_stream(&state, body).await;
+            return forward_passthrough_stream(&state, &*provider, body, permit).await;
         } else {
-            return forward_passthrough_json(&state, body).await.into_response();
+            return forward_passthrough_json(&state, &*provider, body)
+                .await
+                .into_response();
         }
     }
 
+    metrics::record_tool_mode();
+
     // Messages parsing.
     let mut messages: Vec<Message> = match body.get("messages") {
         Some(msgs) => match serde_json::from_value(msgs.clone()) {
@@ -262,17 +464,25 @@ async fn proxy_handler(
         );
     }
 
-    // Prepare body for Positron: remove tools/tool_choice, update messages.
-    body.as_object_mut().map(|map| {
+    let wants_stream = body
+        .get("stream")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if wants_stream && !provider.supports_streaming() {
+        return unsupported_streaming_response();
+    }
+
+    // Strip the OpenAI-shaped tool fields, then let the provider build its
+    // own native request body from the (tool-instruction-injected) messages.
+    if let Some(map) = body.as_object_mut() {
         map.remove("tools");
         map.remove("tool_choice");
-        // Force non-streaming so we can buffer and retry reliably.
-        map.insert("stream".to_string(), Value::Bool(false));
-    });
-    body["messages"] = serde_json::to_value(&messages).unwrap_or_else(|_| Value::Array(vec![]));
+        map.remove("messages");
+    }
+    body = provider.build_body(&messages, &tools, &body);
 
     // Compile schema validators once per request.
-    let validators = match compile_validators(&tools) {
+    let validators = Arc::new(match compile_validators(&tools) {
         Ok(v) => v,
         Err(e) => {
             return (
@@ -281,43 +491,201 @@ async fn proxy_handler(
             )
                 .into_response();
         }
-    };
+    });
 
-    let mut attempt = 0usize;
-    while attempt < state.max_retries {
-        match forward_and_handle(
+    if wants_stream {
+        body["stream"] = Value::Bool(true);
+        return forward_and_handle_stream(state.clone(), provider, body, tools, validators, permit)
+            .await;
+    }
+
+    // Non-streaming mode buffers the whole completion so a bad `<tool_code>`
+    // block can be retried with corrective feedback.
+    body["stream"] = Value::Bool(false);
+
+    let mut steps = 0usize;
+    loop {
+        let resp_value = match run_turn_with_retries(
             &state,
+            &*provider,
             &mut body,
             &mut messages,
             &tools,
             &validators,
-            attempt,
         )
         .await
         {
-            Ok(resp) => return resp.into_response(),
-            Err(e) => {
-                eprintln!("Attempt {} error: {}", attempt + 1, e);
+            Ok(v) => v,
+            Err(resp) => return resp,
+        };
+
+        let tool_calls = resp_value["choices"][0]["message"]["tool_calls"].clone();
+        let has_calls = tool_calls.as_array().is_some_and(|a| !a.is_empty());
+
+        if !has_calls || !opts.execute_tools || steps >= state.max_steps {
+            return (StatusCode::OK, Json(resp_value)).into_response();
+        }
+
+        execute_tool_step(&state, &mut messages, &tool_calls).await;
+        body = provider.build_body(&messages, &tools, &body);
+        steps += 1;
+    }
+}
+
+/// Why one attempt against Positron failed, so the retry loop can treat the
+/// two cases differently: a formatting failure (bad `<tool_code>`) is the
+/// model's fault and is retried immediately with a corrective message, while
+/// a transport failure (timeout, connection error, 5xx) is Positron's fault
+/// and is retried with exponential backoff instead of hammering it.
+enum AttemptError {
+    Transport(String),
+    Formatting(String),
+}
+
+impl std::fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttemptError::Transport(e) => write!(f, "transport error: {e}"),
+            AttemptError::Formatting(e) => write!(f, "formatting error: {e}"),
+        }
+    }
+}
+
+/// Exponential backoff with full jitter: a random delay between 0 and
+/// `base * 2^attempt`, capped at `retry_max_delay_ms`.
+fn backoff_delay(state: &AppState, attempt: usize) -> Duration {
+    let exp = state
+        .retry_base_delay_ms
+        .saturating_mul(1u64 << attempt.min(16));
+    let capped = exp.min(state.retry_max_delay_ms).max(1);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=capped))
+}
+
+/// Runs the buffered retry loop for a single conversational turn, returning
+/// the raw Positron-shaped response value on success.
+async fn run_turn_with_retries(
+    state: &AppState,
+    provider: &dyn Provider,
+    body: &mut Value,
+    messages: &mut Vec<Message>,
+    tools: &[Tool],
+    validators: &HashMap<String, JSONSchema>,
+) -> Result<Value, Response> {
+    let mut format_attempt = 0usize;
+    let mut transport_attempt = 0usize;
+
+    loop {
+        metrics::record_attempt();
+        match forward_and_handle(
+            state,
+            provider,
+            body,
+            messages,
+            tools,
+            validators,
+            format_attempt,
+        )
+        .await
+        {
+            Ok((_, Json(value))) => return Ok(value),
+            Err(AttemptError::Formatting(e)) => {
+                warn!(attempt = format_attempt + 1, error = %e, "formatting attempt failed");
+                format_attempt += 1;
             }
+            Err(AttemptError::Transport(e)) => {
+                transport_attempt += 1;
+                metrics::record_transport_error();
+                warn!(attempt = transport_attempt, error = %e, "transport attempt failed");
+                if state.count_transport_errors_against_retries {
+                    format_attempt += 1;
+                }
+                if transport_attempt >= state.max_retries || format_attempt >= state.max_retries {
+                    metrics::record_max_retries_exceeded();
+                    return Err((
+                        StatusCode::BAD_GATEWAY,
+                        Json(json!({ "error": format!("Positron unavailable after {transport_attempt} attempts: {e}") })),
+                    )
+                        .into_response());
+                }
+                tokio::time::sleep(backoff_delay(state, transport_attempt)).await;
+                continue;
+            }
+        }
+
+        if format_attempt >= state.max_retries {
+            metrics::record_max_retries_exceeded();
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Max retries exceeded. Model failed to format tool call." })),
+            )
+                .into_response());
         }
-        attempt += 1;
     }
+}
+
+/// Appends the assistant's tool-call message, executes each call against the
+/// registered `ToolExecutor`s (if any), and appends a `role:"tool"` message
+/// per result so the next turn can see the outcome.
+async fn execute_tool_step(state: &AppState, messages: &mut Vec<Message>, tool_calls: &Value) {
+    let parsed_calls: Vec<ToolCall> =
+        serde_json::from_value(tool_calls.clone()).unwrap_or_default();
 
+    messages.push(Message {
+        role: "assistant".into(),
+        content: None,
+        tool_calls: Some(parsed_calls.clone()),
+        name: None,
+        tool_call_id: None,
+    });
+
+    for call in parsed_calls {
+        let args: Value = serde_json::from_str(&call.function.arguments).unwrap_or(Value::Null);
+        let result = match state.tool_executors.get(&call.function.name) {
+            Some(executor) => executor
+                .call(args)
+                .await
+                .unwrap_or_else(|e| json!({ "error": e })),
+            None => {
+                json!({ "error": format!("no executor registered for tool '{}'", call.function.name) })
+            }
+        };
+
+        messages.push(Message {
+            role: "tool".into(),
+            content: Some(Value::String(result.to_string())),
+            tool_calls: None,
+            name: Some(call.function.name),
+            tool_call_id: Some(call.id),
+        });
+    }
+}
+
+/// The SSE scanner in `forward_and_handle_stream`/`forward_passthrough_stream`
+/// only understands the OpenAI delta shape; a provider whose wire format
+/// differs must reject `stream:true` outright rather than silently
+/// returning a stream with no content deltas.
+fn unsupported_streaming_response() -> Response {
     (
         StatusCode::BAD_REQUEST,
-        Json(json!({ "error": "Max retries exceeded. Model failed to format tool call." })),
+        Json(json!({ "error": "The configured provider does not support streaming responses." })),
     )
         .into_response()
 }
 
-async fn forward_passthrough_json(state: &AppState, body: Value) -> (StatusCode, Json<Value>) {
+async fn forward_passthrough_json(
+    state: &AppState,
+    provider: &dyn Provider,
+    body: Value,
+) -> (StatusCode, Json<Value>) {
+    let timer = metrics::UpstreamTimer::start();
     let resp = state
         .http
-        .post(format!("{}/chat/completions", state.positron_url))
+        .post(format!("{}{}", state.positron_url, provider.endpoint()))
         .header("Authorization", format!("Bearer {}", state.positron_key))
         .json(&body)
         .send()
         .await;
+    timer.stop();
 
     match resp {
         Ok(r) => match r.json::<Value>().await {
@@ -334,17 +702,27 @@ async fn forward_passthrough_json(state: &AppState, body: Value) -> (StatusCode,
     }
 }
 
-async fn forward_passthrough_stream(state: &AppState, body: Value) -> Response {
+async fn forward_passthrough_stream(
+    state: &AppState,
+    provider: &dyn Provider,
+    body: Value,
+    permit: OwnedSemaphorePermit,
+) -> Response {
+    let timer = metrics::UpstreamTimer::start();
     let req = match state
         .http
-        .post(format!("{}/chat/completions", state.positron_url))
+        .post(format!("{}{}", state.positron_url, provider.endpoint()))
         .header("Authorization", format!("Bearer {}", state.positron_key))
         .json(&body)
         .send()
         .await
     {
-        Ok(r) => r,
+        Ok(r) => {
+            timer.stop();
+            r
+        }
         Err(e) => {
+            timer.stop();
             return (
                 StatusCode::BAD_GATEWAY,
                 Json(json!({ "error": format!("Positron request failed: {e}") })),
@@ -355,35 +733,54 @@ async fn forward_passthrough_stream(state: &AppState, body: Value) -> Response {
 
     let stream = req
         .bytes_stream()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("stream error: {e}")));
+        .map_err(|e| std::io::Error::other(format!("stream error: {e}")));
+    // Carry the permit as fold state so it isn't released until the upstream
+    // stream is fully drained (or dropped), not when this function returns.
+    let stream = stream::unfold((permit, stream), |(permit, mut stream)| async move {
+        stream.next().await.map(|item| (item, (permit, stream)))
+    });
     let body = Body::from_stream(stream);
     Response::new(body)
 }
 
+#[instrument(skip(state, provider, req_body, messages, tools, validators))]
 async fn forward_and_handle(
     state: &AppState,
+    provider: &dyn Provider,
     req_body: &mut Value,
     messages: &mut Vec<Message>,
     tools: &[Tool],
     validators: &HashMap<String, JSONSchema>,
     attempt: usize,
-) -> Result<(StatusCode, Json<Value>), String> {
+) -> Result<(StatusCode, Json<Value>), AttemptError> {
+    let timer = metrics::UpstreamTimer::start();
     let response = state
         .http
-        .post(format!("{}/chat/completions", state.positron_url))
+        .post(format!("{}{}", state.positron_url, provider.endpoint()))
         .header("Authorization", format!("Bearer {}", state.positron_key))
         .json(req_body)
         .send()
         .await
-        .map_err(|e| format!("Positron call failed: {e}"))?;
+        .map_err(|e| AttemptError::Transport(format!("Positron call failed: {e}")))?;
+    timer.stop();
 
-    let mut resp_json: CompletionResponse = response
+    let status = response.status();
+    if status.is_server_error() {
+        return Err(AttemptError::Transport(format!(
+            "Positron returned {status}"
+        )));
+    }
+
+    let raw: Value = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse Positron response: {e}"))?;
+        .map_err(|e| AttemptError::Transport(format!("Failed to parse Positron response: {e}")))?;
+    let mut resp_json = provider
+        .parse_response(raw)
+        .map_err(AttemptError::Formatting)?;
 
     if resp_json.choices.is_empty() {
-        return Err("No choices returned".into());
+        return Err(AttemptError::Formatting("No choices returned".into()));
     }
 
     let content_opt = resp_json.choices[0]
@@ -400,52 +797,413 @@ async fn forward_and_handle(
         ));
     };
 
-    if let Some(caps) = state.tool_regex.captures(&content) {
-        let raw_json = caps.get(1).map(|m| m.as_str()).unwrap_or_default().trim();
+    let blocks: Vec<String> = state
+        .tool_regex
+        .captures_iter(&content)
+        .map(|caps| {
+            caps.get(1)
+                .map(|m| m.as_str())
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .collect();
 
-        let repaired_str = raw_json.to_string();
+    if blocks.is_empty() {
+        // No tool tags found; return as-is.
+        let value = serde_json::to_value(&resp_json)
+            .map_err(|e| AttemptError::Formatting(e.to_string()))?;
+        return Ok((StatusCode::OK, Json(value)));
+    }
 
-        let repaired: Value = match serde_json::from_str(&repaired_str)
-            .or_else(|_| json5::from_str(&repaired_str))
-        {
+    let mut tool_calls = Vec::with_capacity(blocks.len());
+    let mut failures = Vec::new();
+
+    for (idx, raw_json) in blocks.iter().enumerate() {
+        let parsed: Value = match parse_tool_call_json(raw_json) {
             Ok(v) => v,
             Err(e) => {
-                push_retry_messages(messages, content, format!("Output is not valid JSON: {e}"));
-                req_body["messages"] =
-                    serde_json::to_value(&messages).unwrap_or_else(|_| Value::Array(vec![]));
-                return Err(format!("Attempt {attempt} repair failed: {e}"));
+                metrics::record_tool_validation_failure("invalid_json");
+                failures.push(format!("block {idx}: output is not valid JSON: {e}"));
+                continue;
             }
         };
 
-        let (valid, error_msg, name, args) = validate_schema_and_args(&repaired, tools, validators);
-        if valid {
-            let arguments = serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string());
-            let tool_call = ToolCall {
-                id: format!("call_{}", current_millis()),
-                type_field: "function".into(),
-                function: ToolCallFunction { name, arguments },
+        let (valid, error_msg, name, args) = validate_schema_and_args(&parsed, tools, validators);
+        if !valid {
+            failures.push(format!("block {idx} ({name}): {error_msg}"));
+            continue;
+        }
+
+        let arguments = serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string());
+        tool_calls.push(ToolCall {
+            id: format!("call_{}_{idx}", current_millis()),
+            type_field: "function".into(),
+            function: ToolCallFunction { name, arguments },
+        });
+    }
+
+    if !failures.is_empty() {
+        push_retry_messages(
+            messages,
+            content,
+            format!(
+                "SYSTEM ERROR: {} of {} tool call(s) failed: {}. Try again using <tool_code>.",
+                failures.len(),
+                blocks.len(),
+                failures.join("; ")
+            ),
+        );
+        *req_body = provider.build_body(messages, tools, req_body);
+        return Err(AttemptError::Formatting(format!(
+            "Attempt {attempt} logic error: {}",
+            failures.join("; ")
+        )));
+    }
+
+    resp_json.choices[0].message.tool_calls = Some(tool_calls);
+    resp_json.choices[0].message.content = None;
+
+    let value =
+        serde_json::to_value(&resp_json).map_err(|e| AttemptError::Formatting(e.to_string()))?;
+    Ok((StatusCode::OK, Json(value)))
+}
+
+/// Tracks where we are in the model's streamed text relative to a
+/// `<tool_code>...</tool_code>` block, which may straddle several SSE frames.
+#[derive(Default)]
+struct ToolCodeScanner {
+    in_tool_code: bool,
+    tool_buffer: String,
+    /// Text held back because it could be the start of `<tool_code>` /
+    /// `</tool_code>` and we haven't seen enough of it yet to be sure.
+    pending: String,
+}
+
+enum ScanEvent {
+    /// Plain text the client should see immediately.
+    Text(String),
+    /// A full `<tool_code>...</tool_code>` block just closed.
+    ToolCodeComplete(String),
+}
+
+impl ToolCodeScanner {
+    /// Feed a new content delta in, draining as many `ScanEvent`s as can be
+    /// determined from the data seen so far.
+    fn push(&mut self, delta: &str, out: &mut Vec<ScanEvent>) {
+        self.pending.push_str(delta);
+
+        loop {
+            if self.in_tool_code {
+                if let Some(idx) = self.pending.find(TOOL_CLOSE_TAG) {
+                    self.tool_buffer.push_str(&self.pending[..idx]);
+                    self.pending = self.pending[idx + TOOL_CLOSE_TAG.len()..].to_string();
+                    self.in_tool_code = false;
+                    out.push(ScanEvent::ToolCodeComplete(std::mem::take(
+                        &mut self.tool_buffer,
+                    )));
+                    continue;
+                }
+
+                // Buffer the block's text, holding back only a suffix that
+                // could still grow into the close tag.
+                let safe = partial_suffix_match_len(&self.pending, TOOL_CLOSE_TAG);
+                let flush_to = self.pending.len() - safe;
+                self.tool_buffer.push_str(&self.pending[..flush_to]);
+                self.pending = self.pending[flush_to..].to_string();
+                break;
+            }
+
+            if let Some(idx) = self.pending.find(TOOL_OPEN_TAG) {
+                if idx > 0 {
+                    out.push(ScanEvent::Text(self.pending[..idx].to_string()));
+                }
+                self.pending = self.pending[idx + TOOL_OPEN_TAG.len()..].to_string();
+                self.in_tool_code = true;
+                continue;
+            }
+
+            // No open tag yet. Only hold back a suffix that could still grow
+            // into one; forward everything else verbatim.
+            let safe = partial_suffix_match_len(&self.pending, TOOL_OPEN_TAG);
+            let flush_to = self.pending.len() - safe;
+            if flush_to > 0 {
+                out.push(ScanEvent::Text(self.pending[..flush_to].to_string()));
+                self.pending = self.pending[flush_to..].to_string();
+            }
+            break;
+        }
+    }
+}
+
+/// Length of the longest suffix of `s` that is also a prefix of `tag` (and
+/// therefore might complete into `tag` once more input arrives).
+fn partial_suffix_match_len(s: &str, tag: &str) -> usize {
+    let max = s.len().min(tag.len() - 1);
+    for len in (1..=max).rev() {
+        if tag.as_bytes().starts_with(&s.as_bytes()[s.len() - len..]) {
+            return len;
+        }
+    }
+    0
+}
+
+fn sse_event(value: Value) -> Bytes {
+    Bytes::from(format!("data: {value}\n\n"))
+}
+
+fn sse_content_chunk(content: &str) -> Bytes {
+    sse_event(json!({
+        "object": "chat.completion.chunk",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": content },
+            "finish_reason": Value::Null,
+        }],
+    }))
+}
+
+/// Streaming counterpart of `forward_and_handle`: keeps the SSE connection to
+/// Positron open, forwards plain-text deltas as they arrive, and reassembles
+/// a `<tool_code>` block into synthetic OpenAI-style tool-call chunks once it
+/// closes. Retries aren't possible mid-stream, so a validation failure here
+/// just emits a single error chunk instead of looping.
+async fn forward_and_handle_stream(
+    state: AppState,
+    provider: Box<dyn Provider>,
+    req_body: Value,
+    tools: Vec<Tool>,
+    validators: Arc<HashMap<String, JSONSchema>>,
+    permit: OwnedSemaphorePermit,
+) -> Response {
+    let timer = metrics::UpstreamTimer::start();
+    let upstream = match state
+        .http
+        .post(format!("{}{}", state.positron_url, provider.endpoint()))
+        .header("Authorization", format!("Bearer {}", state.positron_key))
+        .json(&req_body)
+        .send()
+        .await
+    {
+        Ok(r) => {
+            timer.stop();
+            r
+        }
+        Err(e) => {
+            timer.stop();
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({ "error": format!("Positron request failed: {e}") })),
+            )
+                .into_response();
+        }
+    };
+
+    // Unlike the non-streaming path, an error status here wouldn't surface
+    // any other way: the body isn't SSE, so every frame would fail to parse
+    // and the client would see a bare 200 + [DONE] with no content.
+    let status = upstream.status();
+    if !status.is_success() {
+        let upstream_body = upstream.text().await.unwrap_or_default();
+        let error_stream = stream::iter(vec![
+            Ok::<Bytes, std::io::Error>(sse_event(
+                json!({ "error": { "message": format!("Positron returned {status}: {upstream_body}") } }),
+            )),
+            Ok(Bytes::from_static(b"data: [DONE]\n\n")),
+        ]);
+        return Response::builder()
+            .header("content-type", "text/event-stream")
+            .body(Body::from_stream(error_stream))
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(32);
+
+    tokio::spawn(async move {
+        // Held for the task's lifetime so the concurrency slot isn't freed
+        // until the upstream stream has been fully forwarded.
+        let _permit = permit;
+        let mut byte_stream = upstream.bytes_stream();
+        let mut line_buf = String::new();
+        let mut scanner = ToolCodeScanner::default();
+        let mut events = Vec::new();
+
+        while let Some(chunk) = byte_stream.next().await {
+            let chunk = match chunk {
+                Ok(c) => c,
+                Err(e) => {
+                    let _ = tx
+                        .send(Ok(sse_event(json!({ "error": { "message": format!("upstream stream error: {e}") } }))))
+                        .await;
+                    break;
+                }
             };
+            line_buf.push_str(&String::from_utf8_lossy(&chunk));
 
-            resp_json.choices[0].message.tool_calls = Some(vec![tool_call]);
-            resp_json.choices[0].message.content = None;
+            while let Some(pos) = line_buf.find("\n\n") {
+                let event = line_buf[..pos].to_string();
+                line_buf = line_buf[pos + 2..].to_string();
 
-            let value = serde_json::to_value(&resp_json).map_err(|e| e.to_string())?;
-            return Ok((StatusCode::OK, Json(value)));
-        } else {
-            push_retry_messages(
-                messages,
-                content,
-                format!("SYSTEM ERROR: {error_msg}. Try again using <tool_code>."),
-            );
-            req_body["messages"] =
-                serde_json::to_value(&messages).unwrap_or_else(|_| Value::Array(vec![]));
-            return Err(format!("Attempt {attempt} logic error: {error_msg}"));
+                let Some(data) = event
+                    .strip_prefix("data: ")
+                    .or_else(|| event.strip_prefix("data:"))
+                else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    continue;
+                }
+
+                let Ok(parsed) = serde_json::from_str::<Value>(data) else {
+                    continue;
+                };
+                let Some(delta) = parsed
+                    .get("choices")
+                    .and_then(|c| c.get(0))
+                    .and_then(|c| c.get("delta"))
+                    .and_then(|d| d.get("content"))
+                    .and_then(|c| c.as_str())
+                else {
+                    continue;
+                };
+
+                events.clear();
+                scanner.push(delta, &mut events);
+                for event in events.drain(..) {
+                    match event {
+                        ScanEvent::Text(text) => {
+                            if tx.send(Ok(sse_content_chunk(&text))).await.is_err() {
+                                return;
+                            }
+                        }
+                        ScanEvent::ToolCodeComplete(raw) => {
+                            if !emit_tool_call(&tx, raw.trim(), &tools, &validators).await {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
         }
+
+        if scanner.in_tool_code {
+            let _ = tx
+                .send(Ok(sse_event(
+                    json!({ "error": { "message": "stream ended mid <tool_code> block" } }),
+                )))
+                .await;
+        } else if !scanner.pending.is_empty() {
+            // Trailing text that was held back as a possible partial match on
+            // `<tool_code>` but never completed into one; it's plain text and
+            // must still reach the client.
+            let trailing = std::mem::take(&mut scanner.pending);
+            let _ = tx.send(Ok(sse_content_chunk(&trailing))).await;
+        }
+        let _ = tx.send(Ok(Bytes::from_static(b"data: [DONE]\n\n"))).await;
+    });
+
+    let body_stream = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    });
+    Response::builder()
+        .header("content-type", "text/event-stream")
+        .body(Body::from_stream(body_stream))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Parses and validates one completed `<tool_code>` block during a stream
+/// and emits the synthetic tool-call chunks (or a single error chunk on
+/// failure). Returns `false` if the client disconnected.
+async fn emit_tool_call(
+    tx: &mpsc::Sender<Result<Bytes, std::io::Error>>,
+    raw_json: &str,
+    tools: &[Tool],
+    validators: &HashMap<String, JSONSchema>,
+) -> bool {
+    let parsed: Value = match parse_tool_call_json(raw_json) {
+        Ok(v) => v,
+        Err(e) => {
+            metrics::record_tool_validation_failure("invalid_json");
+            let _ = tx
+                .send(Ok(sse_event(
+                    json!({ "error": { "message": format!("Output is not valid JSON: {e}") } }),
+                )))
+                .await;
+            return !tx.is_closed();
+        }
+    };
+
+    let (valid, error_msg, name, args) = validate_schema_and_args(&parsed, tools, validators);
+    if !valid {
+        return tx
+            .send(Ok(sse_event(json!({ "error": { "message": error_msg } }))))
+            .await
+            .is_ok();
     }
 
-    // No tool tags found; return as-is.
-    let value = serde_json::to_value(&resp_json).map_err(|e| e.to_string())?;
-    Ok((StatusCode::OK, Json(value)))
+    let id = format!("call_{}", current_millis());
+    let arguments = serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string());
+
+    let sent = tx
+        .send(Ok(sse_event(json!({
+            "object": "chat.completion.chunk",
+            "choices": [{
+                "index": 0,
+                "delta": { "tool_calls": [{
+                    "index": 0,
+                    "id": id,
+                    "type": "function",
+                    "function": { "name": name },
+                }]},
+                "finish_reason": Value::Null,
+            }],
+        }))))
+        .await
+        .is_ok();
+    if !sent {
+        return false;
+    }
+
+    let sent = tx
+        .send(Ok(sse_event(json!({
+            "object": "chat.completion.chunk",
+            "choices": [{
+                "index": 0,
+                "delta": { "tool_calls": [{
+                    "index": 0,
+                    "function": { "arguments": arguments },
+                }]},
+                "finish_reason": Value::Null,
+            }],
+        }))))
+        .await
+        .is_ok();
+    if !sent {
+        return false;
+    }
+
+    tx.send(Ok(sse_event(json!({
+        "object": "chat.completion.chunk",
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }],
+    }))))
+    .await
+    .is_ok()
+}
+
+/// Parses a `<tool_code>` block's JSON, tolerating the shapes model output
+/// most often breaks in: `serde_json` first, then JSON5 (trailing commas,
+/// unquoted keys, single quotes), then `json_repair` for output truncated
+/// mid-object, before giving up.
+fn parse_tool_call_json(raw_json: &str) -> Result<Value, String> {
+    if let Ok(v) = serde_json::from_str(raw_json) {
+        return Ok(v);
+    }
+    if let Ok(v) = json5::from_str(raw_json) {
+        return Ok(v);
+    }
+    let repaired = json_repair::repair_json_snippet(raw_json).map_err(|e| e.to_string())?;
+    serde_json::from_str(&repaired).map_err(|e| e.to_string())
 }
 
 fn compile_validators(tools: &[Tool]) -> Result<HashMap<String, JSONSchema>, String> {
@@ -465,6 +1223,7 @@ fn validate_schema_and_args(
     validators: &HashMap<String, JSONSchema>,
 ) -> (bool, String, String, Value) {
     if !tool_json.is_object() {
+        metrics::record_tool_validation_failure("invalid_json");
         return (
             false,
             "Output is not a valid JSON object.".into(),
@@ -479,6 +1238,7 @@ fn validate_schema_and_args(
         .map(|s| s.to_string());
 
     let Some(name) = tool_name else {
+        metrics::record_tool_validation_failure("missing_name");
         return (
             false,
             "JSON missing 'name' field.".into(),
@@ -492,6 +1252,7 @@ fn validate_schema_and_args(
         .map(|t| t.function.name.clone())
         .collect();
     if !valid_names.contains(&name) {
+        metrics::record_tool_validation_failure("unknown_tool");
         return (
             false,
             format!("Tool '{name}' does not exist. Available tools: {valid_names:?}"),
@@ -511,6 +1272,7 @@ fn validate_schema_and_args(
             .map_err(|errors| errors.map(|e| e.to_string()).collect::<Vec<_>>().join("; "));
 
         if let Err(combined) = validation {
+            metrics::record_tool_validation_failure("schema_failed");
             return (
                 false,
                 format!("Arguments failed validation: {combined}"),
@@ -520,6 +1282,7 @@ fn validate_schema_and_args(
         }
     }
 
+    info!(tool = %name, "tool call validated");
     (true, String::new(), name, args)
 }
 
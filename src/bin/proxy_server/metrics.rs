@@ -0,0 +1,69 @@
+use std::time::Instant;
+
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+/// Installs the global Prometheus recorder and returns a handle whose
+/// `render()` produces the text exposed on `/metrics`.
+pub(crate) fn install_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .install_recorder()
+        .expect("failed to install Prometheus recorder")
+}
+
+/// One request hit `/v1/chat/completions`, before routing decides whether
+/// it's pass-through or tool mode.
+pub(crate) fn record_request() {
+    counter!("llmproxy_requests_total").increment(1);
+}
+
+/// A request was served without the `<tool_code>` formatting/retry pipeline.
+pub(crate) fn record_passthrough() {
+    counter!("llmproxy_requests_by_mode_total", "mode" => "passthrough").increment(1);
+}
+
+/// A request went through the `<tool_code>` formatting/retry pipeline.
+pub(crate) fn record_tool_mode() {
+    counter!("llmproxy_requests_by_mode_total", "mode" => "tool_mode").increment(1);
+}
+
+/// One attempt (initial or retry) was made against the upstream provider.
+pub(crate) fn record_attempt() {
+    counter!("llmproxy_attempts_total").increment(1);
+}
+
+/// All retries for a turn were exhausted without a valid tool call.
+pub(crate) fn record_max_retries_exceeded() {
+    counter!("llmproxy_max_retries_exceeded_total").increment(1);
+}
+
+/// A call to the upstream provider failed at the transport level (timeout,
+/// connection error, 5xx) rather than via invalid `<tool_code>` output.
+pub(crate) fn record_transport_error() {
+    counter!("llmproxy_transport_errors_total").increment(1);
+}
+
+/// A request was rejected with `503` because `MAX_CONCURRENCY` requests were
+/// already in flight.
+pub(crate) fn record_concurrency_limited() {
+    counter!("llmproxy_concurrency_limited_total").increment(1);
+}
+
+/// A `<tool_code>` block failed to become a valid tool call. `reason` is one
+/// of `invalid_json`, `missing_name`, `unknown_tool`, `schema_failed`.
+pub(crate) fn record_tool_validation_failure(reason: &'static str) {
+    counter!("llmproxy_tool_validation_failures_total", "reason" => reason).increment(1);
+}
+
+/// Records how long an upstream provider call took.
+pub(crate) struct UpstreamTimer(Instant);
+
+impl UpstreamTimer {
+    pub(crate) fn start() -> Self {
+        Self(Instant::now())
+    }
+
+    pub(crate) fn stop(self) {
+        histogram!("llmproxy_upstream_latency_seconds").record(self.0.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::{Choice, CompletionResponse, Message, Tool};
+
+/// Abstracts over upstream chat-completion APIs so the `<tool_code>`
+/// injection/validation pipeline can front models whose native request body
+/// and tool-call shape differ from OpenAI's.
+pub(crate) trait Provider: Send + Sync {
+    /// Builds the provider-native request body. `base_body` carries the other
+    /// top-level fields (model, temperature, ...): on the first call, the
+    /// client's body with `tools`, `tool_choice` and `messages` stripped; on
+    /// later calls, the body this provider built before, whose provider-set
+    /// fields are overwritten.
+    fn build_body(&self, messages: &[Message], tools: &[Tool], base_body: &Value) -> Value;
+
+    /// Normalizes a provider-native response into the OpenAI-shaped
+    /// `CompletionResponse` the rest of the proxy works with.
+    fn parse_response(&self, raw: Value) -> Result<CompletionResponse, String>;
+
+    /// Path, relative to the configured base URL, completions are POSTed to.
+    fn endpoint(&self) -> &'static str;
+
+    /// Whether this provider's streamed response is the OpenAI SSE delta
+    /// shape (`choices[0].delta.content`) the proxy's scanner understands.
+    /// Providers whose wire format differs (Claude, Cohere) must override
+    /// this to `false` so `stream:true` is rejected instead of silently
+    /// producing an empty stream.
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+}
+
+/// Resolves a `PROVIDER` env var or `x-provider` header value to a `Provider`.
+/// Unknown or missing names fall back to `OpenAiProvider`.
+pub(crate) fn provider_from_name(name: &str) -> Box<dyn Provider> {
+    match name.to_ascii_lowercase().as_str() {
+        "claude" => Box::new(ClaudeProvider),
+        "cohere" => Box::new(CohereProvider),
+        _ => Box::new(OpenAiProvider),
+    }
+}
+
+fn text_content(message: &Message) -> String {
+    message
+        .content
+        .as_ref()
+        .and_then(|c| c.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn raw_object_extra(raw: &Value) -> HashMap<String, Value> {
+    raw.as_object()
+        .cloned()
+        .map(|m| m.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// The proxy's native shape: OpenAI's `/chat/completions` request/response.
+pub(crate) struct OpenAiProvider;
+
+impl Provider for OpenAiProvider {
+    fn build_body(&self, messages: &[Message], _tools: &[Tool], base_body: &Value) -> Value {
+        let mut body = base_body.clone();
+        body["messages"] = serde_json::to_value(messages).unwrap_or_else(|_| Value::Array(vec![]));
+        body
+    }
+
+    fn parse_response(&self, raw: Value) -> Result<CompletionResponse, String> {
+        serde_json::from_value(raw).map_err(|e| format!("Failed to parse OpenAI response: {e}"))
+    }
+
+    fn endpoint(&self) -> &'static str {
+        "/chat/completions"
+    }
+}
+
+/// Builds the Anthropic `content` block array for an assistant message that
+/// carries `tool_calls`: an optional leading text block, then one
+/// `tool_use` block per call so a later `tool_result`'s `tool_use_id` has a
+/// matching id to reference.
+fn claude_tool_use_content(message: &Message) -> Vec<Value> {
+    let mut content = Vec::new();
+
+    let text = text_content(message);
+    if !text.is_empty() {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+
+    for call in message.tool_calls.iter().flatten() {
+        let input: Value =
+            serde_json::from_str(&call.function.arguments).unwrap_or_else(|_| json!({}));
+        content.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.function.name,
+            "input": input,
+        }));
+    }
+
+    content
+}
+
+/// Anthropic's Messages API rejects requests with no `max_tokens`; OpenAI's
+/// field is optional, so fall back to this when the client didn't send one.
+const CLAUDE_DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Translates to/from Anthropic's Messages API: the system role is a
+/// top-level `system` string rather than a message, and tool results are
+/// `tool_result` content blocks inside a user turn.
+pub(crate) struct ClaudeProvider;
+
+impl Provider for ClaudeProvider {
+    fn build_body(&self, messages: &[Message], _tools: &[Tool], base_body: &Value) -> Value {
+        let mut system = String::new();
+        let mut turns = Vec::with_capacity(messages.len());
+
+        for m in messages {
+            match m.role.as_str() {
+                "system" => {
+                    if !system.is_empty() {
+                        system.push_str("\n\n");
+                    }
+                    system.push_str(&text_content(m));
+                }
+                "tool" => turns.push(json!({
+                    "role": "user",
+                    "content": [{
+                        "type": "tool_result",
+                        "tool_use_id": m.tool_call_id,
+                        "content": text_content(m),
+                    }],
+                })),
+                "assistant" if m.tool_calls.is_some() => {
+                    turns.push(json!({
+                        "role": "assistant",
+                        "content": claude_tool_use_content(m),
+                    }));
+                }
+                role => turns.push(json!({ "role": role, "content": text_content(m) })),
+            }
+        }
+
+        let mut body = base_body.clone();
+        body["system"] = Value::String(system);
+        body["messages"] = Value::Array(turns);
+        if body.get("max_tokens").and_then(|v| v.as_u64()).is_none() {
+            body["max_tokens"] = json!(CLAUDE_DEFAULT_MAX_TOKENS);
+        }
+        body
+    }
+
+    fn parse_response(&self, raw: Value) -> Result<CompletionResponse, String> {
+        let text = raw
+            .get("content")
+            .and_then(|c| c.as_array())
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("")
+            })
+            .unwrap_or_default();
+
+        Ok(CompletionResponse {
+            choices: vec![Choice {
+                message: Message {
+                    role: "assistant".into(),
+                    content: Some(Value::String(text)),
+                    tool_calls: None,
+                    name: None,
+                    tool_call_id: None,
+                },
+            }],
+            extra: raw_object_extra(&raw),
+        })
+    }
+
+    fn endpoint(&self) -> &'static str {
+        "/messages"
+    }
+
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+}
+
+/// Cohere's `chat_history` only carries plain turn text; represent an
+/// assistant tool call as a bracketed description so the model still sees
+/// that a tool was invoked, instead of serializing as an empty string.
+fn cohere_turn_text(message: &Message) -> String {
+    let text = text_content(message);
+    if !text.is_empty() {
+        return text;
+    }
+    message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|c| format!("[called tool {} with {}]", c.function.name, c.function.arguments))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Translates to/from Cohere's Chat API: the system prompt is a separate
+/// `preamble`, prior turns form `chat_history`, and the latest turn is the
+/// top-level `message` field.
+pub(crate) struct CohereProvider;
+
+impl Provider for CohereProvider {
+    fn build_body(&self, messages: &[Message], _tools: &[Tool], base_body: &Value) -> Value {
+        let mut preamble = String::new();
+        let mut turns = Vec::new();
+        let mut last_message = String::new();
+
+        for m in messages {
+            match m.role.as_str() {
+                "system" => {
+                    if !preamble.is_empty() {
+                        preamble.push_str("\n\n");
+                    }
+                    preamble.push_str(&text_content(m));
+                }
+                "user" => {
+                    last_message = text_content(m);
+                    turns.push(json!({ "role": "USER", "message": last_message }));
+                }
+                "assistant" => {
+                    last_message = cohere_turn_text(m);
+                    turns.push(json!({ "role": "CHATBOT", "message": last_message }));
+                }
+                "tool" => {
+                    last_message = text_content(m);
+                    turns.push(json!({
+                        "role": "TOOL",
+                        "message": last_message,
+                        "tool_call_id": m.tool_call_id,
+                        "name": m.name,
+                    }));
+                }
+                _ => {}
+            }
+        }
+
+        // The last turn (whatever its role) is the current query Cohere
+        // answers; everything before it is history.
+        turns.pop();
+
+        let mut body = base_body.clone();
+        body["preamble"] = Value::String(preamble);
+        body["chat_history"] = Value::Array(turns);
+        body["message"] = Value::String(last_message);
+        body
+    }
+
+    fn parse_response(&self, raw: Value) -> Result<CompletionResponse, String> {
+        let text = raw
+            .get("text")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(CompletionResponse {
+            choices: vec![Choice {
+                message: Message {
+                    role: "assistant".into(),
+                    content: Some(Value::String(text)),
+                    tool_calls: None,
+                    name: None,
+                    tool_call_id: None,
+                },
+            }],
+            extra: raw_object_extra(&raw),
+        })
+    }
+
+    fn endpoint(&self) -> &'static str {
+        "/chat"
+    }
+
+    fn supports_streaming(&self) -> bool {
+        false
+    }
+}
@@ -9,22 +9,39 @@ pub enum RepairError {
     Parse(String),
 }
 
-/// A minimal, safe JSON "repair" that:
-/// - Ensures the snippet is wrapped in braces if it isn't already.
-/// - Replaces single quotes with double quotes.
-/// - Removes trailing commas in objects/arrays.
+/// Repairs the JSON shapes model output most commonly breaks on:
+/// - Unbalanced `{`/`[` left open by mid-object truncation, and unterminated
+///   strings, fixed by scanning a delimiter stack and appending the matching
+///   closers.
+/// - A trailing comma left by the same truncation, or a dangling key (`"b":`
+///   with no value yet) that would otherwise become invalid once closed.
+/// - Single quotes instead of double quotes.
+/// - A bare (non-object/array) snippet, wrapped in `{}` as a last resort.
 pub fn repair_json_snippet(input: &str) -> Result<String, RepairError> {
     let trimmed = input.trim();
     if trimmed.is_empty() {
         return Err(RepairError::Empty);
     }
 
-    // Heuristic replacements; conservative to avoid mangling valid JSON.
+    let had_delimiter = trimmed.starts_with('{') || trimmed.starts_with('[');
+
     let mut s = trimmed.replace('\'', "\"");
+    let (stack, in_string) = scan_delimiters(&s);
+
+    if in_string {
+        s.push('"');
+    }
+
+    let in_object_context = stack.last() == Some(&'{') || (stack.is_empty() && !had_delimiter);
+    s = drop_dangling_key(&s, in_object_context);
+
+    for open in stack.iter().rev() {
+        s.push(if *open == '{' { '}' } else { ']' });
+    }
+
     s = remove_trailing_commas(&s);
 
-    // If it doesn't start with { or [, assume it's an object.
-    if !s.starts_with('{') && !s.starts_with('[') {
+    if !had_delimiter && stack.is_empty() {
         s = format!("{{{s}}}");
     }
 
@@ -33,6 +50,104 @@ pub fn repair_json_snippet(input: &str) -> Result<String, RepairError> {
     Ok(s)
 }
 
+/// Scans `s` once, tracking the stack of still-open `{`/`[` delimiters and
+/// whether `s` ends mid-string, honoring escapes so quotes and delimiters
+/// inside strings are ignored.
+fn scan_delimiters(s: &str) -> (Vec<char>, bool) {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for ch in s.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match ch {
+            '"' => in_string = true,
+            '{' | '[' => stack.push(ch),
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    (stack, in_string)
+}
+
+/// Drops a trailing dangling object key — a bare `key:` with no value, or a
+/// quoted key with no colon at all yet — so the closers `scan_delimiters`
+/// found produce valid JSON instead of `{"a":1,"b":}` or `{"a":1,"b"}` with a
+/// silently-missing value. Only applies inside object context; a truncated
+/// array element (or a truncated object *value*, which already has its
+/// closing quote restored) is left alone.
+fn drop_dangling_key(s: &str, in_object_context: bool) -> String {
+    let trimmed = s.trim_end();
+    if !in_object_context {
+        return trimmed.to_string();
+    }
+
+    if let Some(before_colon) = trimmed.strip_suffix(':') {
+        return strip_trailing_key(before_colon.trim_end());
+    }
+
+    if trailing_quoted_string_start(trimmed).is_some() && !preceded_by_colon(trimmed) {
+        return strip_trailing_key(trimmed);
+    }
+
+    trimmed.to_string()
+}
+
+/// If `s` ends with a complete quoted string, returns the byte index of its
+/// opening quote.
+fn trailing_quoted_string_start(s: &str) -> Option<usize> {
+    if !s.ends_with('"') {
+        return None;
+    }
+    let bytes = s.as_bytes();
+    let mut i = bytes.len() - 1;
+    while i > 0 {
+        i -= 1;
+        if bytes[i] == b'"' && (i == 0 || bytes[i - 1] != b'\\') {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Whether the quoted string trailing `s` is itself the value half of a
+/// `"key": "value` pair (as opposed to a bare key with no colon yet).
+fn preceded_by_colon(s: &str) -> bool {
+    match trailing_quoted_string_start(s) {
+        Some(start) => s[..start].trim_end().ends_with(':'),
+        None => false,
+    }
+}
+
+/// Removes the trailing quoted key from `s`, along with the comma that
+/// introduced it; an opening brace before the key is kept.
+fn strip_trailing_key(s: &str) -> String {
+    match trailing_quoted_string_start(s) {
+        Some(start) => {
+            let before = s[..start].trim_end();
+            before
+                .strip_suffix(',')
+                .unwrap_or(before)
+                .trim_end()
+                .to_string()
+        }
+        None => s.trim_end().to_string(),
+    }
+}
+
 fn remove_trailing_commas(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     for (i, ch) in s.chars().enumerate() {